@@ -0,0 +1,170 @@
+//! JMESPath abstract syntax tree (AST).
+//!
+//! The AST is produced by the `parser` module and consumed by the
+//! `interpreter` module.
+
+use RcVar;
+
+/// A byte range `[start, end)` into the original expression string.
+///
+/// Every `Ast` node carries a `Span` rather than a single start offset so
+/// that error messages can underline the whole offending token (e.g. the
+/// entire `foo(...)` call or `[::0]` slice) instead of pointing a single
+/// `^` at its first byte.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    /// The byte offset of the first byte covered by this span.
+    pub start: usize,
+    /// The byte offset one past the last byte covered by this span.
+    pub end: usize,
+}
+
+impl Span {
+    /// Creates a new span covering `[start, end)`.
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start: start, end: end }
+    }
+
+    /// Creates a zero-width span at `offset`, used when an end position
+    /// isn't known (e.g. a synthetic node).
+    pub fn point(offset: usize) -> Span {
+        Span { start: offset, end: offset }
+    }
+
+    /// The number of bytes covered by this span, at least 1 so that an
+    /// underline always has something to draw.
+    pub fn len(&self) -> usize {
+        if self.end > self.start { self.end - self.start } else { 1 }
+    }
+
+    /// Returns a span that covers both `self` and `other`.
+    pub fn to(&self, other: Span) -> Span {
+        Span::new(self.start, other.end)
+    }
+}
+
+/// A node in a JMESPath abstract syntax tree.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Ast {
+    /// Compares two nodes using a comparator, returning a boolean.
+    Comparison {
+        comparator: Comparator,
+        lhs: Box<Ast>,
+        rhs: Box<Ast>,
+        span: Span,
+    },
+    /// If `predicate` evaluates to a truthy value, returns the result of
+    /// evaluating `then`. Otherwise returns `null`.
+    Condition {
+        predicate: Box<Ast>,
+        then: Box<Ast>,
+        span: Span,
+    },
+    /// Returns the current node (`@`).
+    Identity { span: Span },
+    /// Used by functions to build an expression reference (`&foo.bar`).
+    Expref { ast: Box<Ast>, span: Span },
+    /// Evaluates `node`, then flattens the result one level.
+    Flatten { node: Box<Ast>, span: Span },
+    /// Calls a function by name, passing along the evaluated arguments.
+    Function {
+        name: String,
+        args: Vec<Ast>,
+        span: Span,
+    },
+    /// Extracts a field by name from a map.
+    Field { name: String, span: Span },
+    /// Extracts an index from an array, supporting negative indices.
+    Index { idx: i32, span: Span },
+    /// Resolves to a literal value (e.g. `` `true` ``).
+    Literal { value: RcVar, span: Span },
+    /// Evaluates to an array built from each evaluated element.
+    MultiList { elements: Vec<Ast>, span: Span },
+    /// Evaluates to a map built from each evaluated key value pair.
+    MultiHash { elements: Vec<KeyValuePair>, span: Span },
+    /// Evaluates to the boolean negation of `node`.
+    Not { node: Box<Ast>, span: Span },
+    /// Evaluates `lhs`, and if it is an array applies `rhs` to each of its
+    /// elements, collecting the non-null results into an array. Evaluates
+    /// to `null` when `lhs` is not an array.
+    Projection {
+        lhs: Box<Ast>,
+        rhs: Box<Ast>,
+        span: Span,
+    },
+    /// Evaluates `lhs`, then evaluates `rhs` using the result of `lhs` as
+    /// the new current node.
+    Subexpr {
+        lhs: Box<Ast>,
+        rhs: Box<Ast>,
+        span: Span,
+    },
+    /// Extracts a slice from an array.
+    Slice {
+        start: Option<i32>,
+        stop: Option<i32>,
+        step: i32,
+        span: Span,
+    },
+    /// Returns the values of an object, discarding keys.
+    ObjectValues { node: Box<Ast>, span: Span },
+    /// Evaluates `lhs`; if truthy, evaluates and returns `rhs`, otherwise
+    /// returns the `lhs` result.
+    And { lhs: Box<Ast>, rhs: Box<Ast>, span: Span },
+    /// Evaluates `lhs`; if truthy, returns it, otherwise evaluates and
+    /// returns `rhs`.
+    Or { lhs: Box<Ast>, rhs: Box<Ast>, span: Span },
+}
+
+impl Ast {
+    /// Returns the span at which this node begins and ends in the
+    /// original expression string.
+    pub fn span(&self) -> Span {
+        match self {
+            &Ast::Comparison { span, .. } |
+            &Ast::Condition { span, .. } |
+            &Ast::Identity { span } |
+            &Ast::Expref { span, .. } |
+            &Ast::Flatten { span, .. } |
+            &Ast::Function { span, .. } |
+            &Ast::Field { span, .. } |
+            &Ast::Index { span, .. } |
+            &Ast::Literal { span, .. } |
+            &Ast::MultiList { span, .. } |
+            &Ast::MultiHash { span, .. } |
+            &Ast::Not { span, .. } |
+            &Ast::Projection { span, .. } |
+            &Ast::Subexpr { span, .. } |
+            &Ast::Slice { span, .. } |
+            &Ast::ObjectValues { span, .. } |
+            &Ast::And { span, .. } |
+            &Ast::Or { span, .. } => span,
+        }
+    }
+
+    /// Returns the byte offset at which this node begins in the original
+    /// expression string.
+    pub fn offset(&self) -> usize {
+        self.span().start
+    }
+}
+
+/// A single `key: value` pair used to build a `MultiHash`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyValuePair {
+    /// The literal key name.
+    pub key: String,
+    /// The expression used to compute the value.
+    pub value: Ast,
+}
+
+/// Binary comparators supported by `Ast::Comparison`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparator {
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+}
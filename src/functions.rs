@@ -0,0 +1,166 @@
+//! Built-in JMESPath functions, and the `Signature`/`ArgumentType` metadata
+//! used to validate a function's arguments before it ever runs.
+
+use interpreter::Context;
+use variable::Variable;
+use {Error, ErrorReason, RcVar, RuntimeError};
+
+/// A JMESPath function that can be invoked by the interpreter.
+pub trait Function {
+    /// Describes the arity and argument types accepted by this function,
+    /// so the interpreter can validate a call before invoking `evaluate`.
+    fn signature(&self) -> Signature;
+
+    /// Evaluates the function against the given arguments. Called only
+    /// after `signature()` has already validated `args`, so `evaluate`
+    /// implementations may assume the arity and argument types match.
+    fn evaluate(&self, args: &[RcVar], ctx: &Context) -> Result<RcVar, Error>;
+}
+
+/// The JMESPath type(s) an argument position will accept.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArgumentType {
+    Any,
+    String,
+    Number,
+    Boolean,
+    Array,
+    Object,
+    Null,
+}
+
+impl ArgumentType {
+    fn name(&self) -> &'static str {
+        match *self {
+            ArgumentType::Any => "any",
+            ArgumentType::String => "string",
+            ArgumentType::Number => "number",
+            ArgumentType::Boolean => "boolean",
+            ArgumentType::Array => "array",
+            ArgumentType::Object => "object",
+            ArgumentType::Null => "null",
+        }
+    }
+
+    fn matches(&self, value: &Variable) -> bool {
+        match (self, value) {
+            (&ArgumentType::Any, _) => true,
+            (&ArgumentType::String, &Variable::String(_)) => true,
+            (&ArgumentType::Number, &Variable::Number(_)) => true,
+            (&ArgumentType::Boolean, &Variable::Bool(_)) => true,
+            (&ArgumentType::Array, &Variable::Array(_)) => true,
+            (&ArgumentType::Object, &Variable::Object(_)) => true,
+            (&ArgumentType::Null, &Variable::Null) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Declares the arity and per-position argument types a `Function`
+/// accepts. `Signature::validate` turns a mismatch into the same
+/// `NotEnoughArguments`/`TooManyArguments`/`InvalidType` errors a
+/// hand-written function would otherwise have to raise itself.
+#[derive(Clone, Debug)]
+pub struct Signature {
+    min_arity: usize,
+    max_arity: usize,
+    /// Allowed types for each argument position. A call with more
+    /// arguments than `arg_types` has entries is checked against the
+    /// last entry, so a trailing `vec![ArgumentType::Any]` covers a
+    /// variadic tail.
+    arg_types: Vec<Vec<ArgumentType>>,
+}
+
+impl Signature {
+    /// Creates a signature accepting between `min_arity` and `max_arity`
+    /// (inclusive) arguments, validated positionally against `arg_types`.
+    pub fn new(min_arity: usize, max_arity: usize, arg_types: Vec<Vec<ArgumentType>>) -> Signature {
+        Signature { min_arity: min_arity, max_arity: max_arity, arg_types: arg_types }
+    }
+
+    /// Creates a signature accepting exactly `arity` arguments.
+    pub fn exact(arity: usize, arg_types: Vec<Vec<ArgumentType>>) -> Signature {
+        Signature::new(arity, arity, arg_types)
+    }
+
+    /// Validates `args` against this signature, returning the matching
+    /// `RuntimeError` for the first problem found.
+    pub fn validate(&self, args: &[RcVar], ctx: &Context) -> Result<(), Error> {
+        try!(validate_arity(args, self.min_arity, self.max_arity, ctx));
+        for (position, arg) in args.iter().enumerate() {
+            let allowed = self.arg_types.get(position).or_else(|| self.arg_types.last());
+            if let Some(allowed) = allowed {
+                if !allowed.is_empty() && !allowed.iter().any(|t| t.matches(arg)) {
+                    let expected = allowed.iter().map(|t| t.name())
+                        .collect::<Vec<_>>().join(" or ");
+                    return Err(Error::from_ctx(ctx, ErrorReason::Runtime(RuntimeError::InvalidType {
+                        expected: expected,
+                        actual: arg.get_type().to_string(),
+                        actual_value: arg.clone(),
+                        position: position,
+                    })));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Validates that `args` has between `min` and `max` (inclusive) elements,
+/// returning the appropriate `RuntimeError` otherwise.
+fn validate_arity(args: &[RcVar], min: usize, max: usize, ctx: &Context) -> Result<(), Error> {
+    if args.len() < min {
+        Err(Error::from_ctx(ctx, ErrorReason::Runtime(RuntimeError::NotEnoughArguments {
+            expected: min,
+            actual: args.len(),
+        })))
+    } else if args.len() > max {
+        Err(Error::from_ctx(ctx, ErrorReason::Runtime(RuntimeError::TooManyArguments {
+            expected: max,
+            actual: args.len(),
+        })))
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the built-in functions registered by `TreeInterpreter::new`.
+pub(crate) fn default_functions() -> ::std::collections::HashMap<String, Box<Function>> {
+    let mut functions: ::std::collections::HashMap<String, Box<Function>> =
+        ::std::collections::HashMap::new();
+    functions.insert("length".to_string(), Box::new(LengthFn));
+    functions.insert("type".to_string(), Box::new(TypeFn));
+    functions
+}
+
+/// `length(subject)` -- returns the length of a string, array, or object.
+pub struct LengthFn;
+
+impl Function for LengthFn {
+    fn signature(&self) -> Signature {
+        Signature::exact(1, vec![vec![ArgumentType::String, ArgumentType::Array, ArgumentType::Object]])
+    }
+
+    fn evaluate(&self, args: &[RcVar], _ctx: &Context) -> Result<RcVar, Error> {
+        let len = match *args[0] {
+            Variable::String(ref s) => s.chars().count(),
+            Variable::Array(ref a) => a.len(),
+            Variable::Object(ref o) => o.len(),
+            _ => unreachable!("Signature::validate guarantees a string, array, or object"),
+        };
+        Ok(::std::rc::Rc::new(Variable::Number(len as f64)))
+    }
+}
+
+/// `type(subject)` -- returns the JMESPath type name of a value.
+pub struct TypeFn;
+
+impl Function for TypeFn {
+    fn signature(&self) -> Signature {
+        Signature::exact(1, vec![vec![ArgumentType::Any]])
+    }
+
+    fn evaluate(&self, args: &[RcVar], _ctx: &Context) -> Result<RcVar, Error> {
+        Ok(::std::rc::Rc::new(Variable::String(args[0].get_type().to_string())))
+    }
+}
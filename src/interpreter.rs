@@ -0,0 +1,290 @@
+//! Recursive tree interpreter used to evaluate a JMESPath `Ast` against a
+//! `Variable` document.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use ast::{Ast, Comparator, Span};
+use functions::{self, Function};
+use variable::Variable;
+use {Coordinates, Error, ErrorReason, RcVar, RuntimeError};
+
+/// The result of evaluating an `Ast` against a `Variable`.
+pub type SearchResult = Result<RcVar, Error>;
+
+/// Evaluates an `Ast` against a `Variable` by recursively walking the tree.
+pub struct TreeInterpreter {
+    functions: HashMap<String, Box<Function>>,
+}
+
+impl TreeInterpreter {
+    /// Creates a new tree interpreter with the default set of built-in
+    /// functions registered.
+    pub fn new() -> TreeInterpreter {
+        TreeInterpreter { functions: functions::default_functions() }
+    }
+
+    /// Creates a tree interpreter from an explicit function map. Used by
+    /// `Runtime` to register custom functions.
+    pub fn from_functions(functions: HashMap<String, Box<Function>>) -> TreeInterpreter {
+        TreeInterpreter { functions: functions }
+    }
+
+    /// Registers a function under `name`, overwriting any existing
+    /// function (built-in or otherwise) registered under the same name.
+    pub fn register_function(&mut self, name: &str, function: Box<Function>) {
+        self.functions.insert(name.to_string(), function);
+    }
+
+    /// Looks up a registered function by name.
+    pub fn function(&self, name: &str) -> Option<&Box<Function>> {
+        self.functions.get(name)
+    }
+
+    /// Interprets an `Ast` against `data`, returning the result.
+    pub fn interpret(&self, data: &RcVar, ast: &Ast, ctx: &mut Context) -> SearchResult {
+        match *ast {
+            Ast::Identity { .. } => Ok(data.clone()),
+            Ast::Literal { ref value, .. } => Ok(value.clone()),
+            Ast::Field { ref name, span } => {
+                ctx.span = span;
+                match **data {
+                    Variable::Object(ref map) => {
+                        Ok(map.get(name).cloned().unwrap_or_else(|| Rc::new(Variable::Null)))
+                    }
+                    _ => Ok(Rc::new(Variable::Null)),
+                }
+            }
+            Ast::Subexpr { ref lhs, ref rhs, .. } => {
+                let result = try!(self.interpret(data, lhs, ctx));
+                self.interpret(&result, rhs, ctx)
+            }
+            Ast::Index { idx, span } => {
+                ctx.span = span;
+                match **data {
+                    Variable::Array(ref array) => Ok(index(array, idx)),
+                    _ => Ok(Rc::new(Variable::Null)),
+                }
+            }
+            Ast::Slice { start, stop, step, span } => {
+                ctx.span = span;
+                if step == 0 {
+                    return Err(Error::from_ctx(ctx,
+                        ErrorReason::Runtime(RuntimeError::InvalidSlice)));
+                }
+                match **data {
+                    Variable::Array(ref array) => {
+                        Ok(Rc::new(Variable::Array(slice(array, start, stop, step))))
+                    }
+                    _ => Ok(Rc::new(Variable::Null)),
+                }
+            }
+            Ast::Flatten { ref node, .. } => {
+                let evaluated = try!(self.interpret(data, node, ctx));
+                match *evaluated {
+                    Variable::Array(ref outer) => {
+                        let mut flattened = Vec::new();
+                        for item in outer {
+                            match **item {
+                                Variable::Array(ref inner) => flattened.extend(inner.clone()),
+                                _ => flattened.push(item.clone()),
+                            }
+                        }
+                        Ok(Rc::new(Variable::Array(flattened)))
+                    }
+                    _ => Ok(Rc::new(Variable::Null)),
+                }
+            }
+            Ast::Projection { ref lhs, ref rhs, .. } => {
+                let evaluated = try!(self.interpret(data, lhs, ctx));
+                match *evaluated {
+                    Variable::Array(ref array) => {
+                        let mut collected = Vec::new();
+                        for element in array {
+                            let result = try!(self.interpret(element, rhs, ctx));
+                            if !result.is_null() {
+                                collected.push(result);
+                            }
+                        }
+                        Ok(Rc::new(Variable::Array(collected)))
+                    }
+                    _ => Ok(Rc::new(Variable::Null)),
+                }
+            }
+            Ast::ObjectValues { ref node, .. } => {
+                let evaluated = try!(self.interpret(data, node, ctx));
+                match *evaluated {
+                    Variable::Object(ref map) => {
+                        Ok(Rc::new(Variable::Array(map.values().cloned().collect())))
+                    }
+                    _ => Ok(Rc::new(Variable::Null)),
+                }
+            }
+            Ast::MultiList { ref elements, .. } => {
+                let mut result = Vec::with_capacity(elements.len());
+                for element in elements {
+                    result.push(try!(self.interpret(data, element, ctx)));
+                }
+                Ok(Rc::new(Variable::Array(result)))
+            }
+            Ast::MultiHash { ref elements, .. } => {
+                let mut map = BTreeMap::new();
+                for kvp in elements {
+                    map.insert(kvp.key.clone(), try!(self.interpret(data, &kvp.value, ctx)));
+                }
+                Ok(Rc::new(Variable::Object(map)))
+            }
+            Ast::Not { ref node, .. } => {
+                let evaluated = try!(self.interpret(data, node, ctx));
+                Ok(Rc::new(Variable::Bool(!evaluated.is_truthy())))
+            }
+            Ast::And { ref lhs, ref rhs, .. } => {
+                let left = try!(self.interpret(data, lhs, ctx));
+                if left.is_truthy() {
+                    self.interpret(data, rhs, ctx)
+                } else {
+                    Ok(left)
+                }
+            }
+            Ast::Or { ref lhs, ref rhs, .. } => {
+                let left = try!(self.interpret(data, lhs, ctx));
+                if left.is_truthy() {
+                    Ok(left)
+                } else {
+                    self.interpret(data, rhs, ctx)
+                }
+            }
+            Ast::Condition { ref predicate, ref then, .. } => {
+                let evaluated = try!(self.interpret(data, predicate, ctx));
+                if evaluated.is_truthy() {
+                    self.interpret(data, then, ctx)
+                } else {
+                    Ok(Rc::new(Variable::Null))
+                }
+            }
+            Ast::Comparison { comparator, ref lhs, ref rhs, span } => {
+                ctx.span = span;
+                let left = try!(self.interpret(data, lhs, ctx));
+                let right = try!(self.interpret(data, rhs, ctx));
+                Ok(Rc::new(Variable::Bool(compare(comparator, &left, &right))))
+            }
+            Ast::Expref { ref ast, .. } => Ok(Rc::new(Variable::Expref((**ast).clone()))),
+            Ast::Function { ref name, ref args, span } => {
+                ctx.span = span;
+                let mut evaluated = Vec::with_capacity(args.len());
+                for arg in args {
+                    evaluated.push(try!(self.interpret(data, arg, ctx)));
+                }
+                match self.function(name) {
+                    Some(function) => {
+                        try!(function.signature().validate(&evaluated, ctx));
+                        function.evaluate(&evaluated, ctx)
+                    }
+                    None => Err(Error::from_ctx(ctx,
+                        ErrorReason::Runtime(RuntimeError::UnknownFunction(name.clone())))),
+                }
+            }
+        }
+    }
+}
+
+/// Resolves a (possibly negative) index against `array`, the way a
+/// JMESPath `Ast::Index` node does, returning `Null` when out of bounds.
+/// Shared with the `vm` module so both backends agree on the semantics.
+pub(crate) fn index(array: &[RcVar], idx: i32) -> RcVar {
+    let resolved = if idx < 0 { array.len() as i32 + idx } else { idx };
+    if resolved < 0 {
+        Rc::new(Variable::Null)
+    } else {
+        array.get(resolved as usize).cloned().unwrap_or_else(|| Rc::new(Variable::Null))
+    }
+}
+
+/// Shared with the `vm` module so both backends agree on slicing semantics.
+pub(crate) fn slice(array: &[RcVar], start: Option<i32>, stop: Option<i32>, step: i32) -> Vec<RcVar> {
+    let len = array.len() as i32;
+    // An omitted bound keeps its sentinel `default` as-is (e.g. the `-1`
+    // "one before the start" sentinel used as the stop when iterating in
+    // reverse) rather than being wrapped like a real negative index, since
+    // wrapping it would collide it with a real array position.
+    let adjust = |v: Option<i32>, default: i32| -> i32 {
+        match v {
+            Some(value) => {
+                let value = if value < 0 { value + len } else { value };
+                value.max(0).min(len)
+            }
+            None => default,
+        }
+    };
+    let mut result = Vec::new();
+    if step > 0 {
+        let mut i = adjust(start, 0);
+        let stop = adjust(stop, len);
+        while i < stop {
+            result.push(array[i as usize].clone());
+            i += step;
+        }
+    } else {
+        let mut i = adjust(start, len - 1);
+        let stop = adjust(stop, -1);
+        while i > stop {
+            if i < len {
+                result.push(array[i as usize].clone());
+            }
+            i += step;
+        }
+    }
+    result
+}
+
+/// Shared with the `vm` module so both backends agree on comparison
+/// semantics.
+pub(crate) fn compare(comparator: Comparator, lhs: &RcVar, rhs: &RcVar) -> bool {
+    use variable::Variable::Number;
+    match comparator {
+        Comparator::Eq => lhs == rhs,
+        Comparator::Ne => lhs != rhs,
+        Comparator::Lt | Comparator::Lte | Comparator::Gt | Comparator::Gte => {
+            match (&**lhs, &**rhs) {
+                (&Number(a), &Number(b)) => match comparator {
+                    Comparator::Lt => a < b,
+                    Comparator::Lte => a <= b,
+                    Comparator::Gt => a > b,
+                    Comparator::Gte => a >= b,
+                    _ => unreachable!(),
+                },
+                _ => false,
+            }
+        }
+    }
+}
+
+/// Carries the state needed to produce accurate errors while interpreting
+/// an expression.
+pub struct Context<'a> {
+    /// The original expression string being evaluated.
+    pub expression: &'a str,
+    /// The interpreter currently performing the evaluation.
+    pub interpreter: &'a TreeInterpreter,
+    /// The span of the AST node currently being evaluated, used to
+    /// underline the *whole* offending token in error messages rather
+    /// than pointing a single caret at its first byte.
+    pub span: Span,
+}
+
+impl<'a> Context<'a> {
+    /// Creates a new context for the given interpreter and expression.
+    pub fn new(interpreter: &'a TreeInterpreter, expression: &'a str) -> Context<'a> {
+        Context {
+            expression: expression,
+            interpreter: interpreter,
+            span: Span::point(0),
+        }
+    }
+
+    /// Creates `Coordinates` underlining the current span.
+    pub fn create_coordinates(&self) -> Coordinates {
+        Coordinates::from_span(self.expression, self.span.start, self.span.end)
+    }
+}
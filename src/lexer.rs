@@ -0,0 +1,233 @@
+//! Tokenizer used by the `parser` module.
+
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use RcVar;
+
+/// A lexical token. Its position is carried by the enclosing `TokenTuple`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Identifier(String),
+    Literal(RcVar),
+    Number(i32),
+    Dot,
+    Star,
+    Flatten,
+    Lbracket,
+    Rbracket,
+    Lbrace,
+    Rbrace,
+    Lparen,
+    Rparen,
+    Pipe,
+    Or,
+    And,
+    Not,
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Comma,
+    Colon,
+    At,
+    Ampersand,
+    Question,
+    Eof,
+}
+
+/// A `Token` paired with the byte range `[offset, end)` it occupies in
+/// the original expression string.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenTuple {
+    pub token: Token,
+    pub offset: usize,
+    pub end: usize,
+}
+
+/// Converts an expression string into a vec of tokens.
+pub fn tokenize(expr: &str) -> Result<Vec<TokenTuple>, String> {
+    let mut lexer = Lexer {
+        chars: expr.char_indices().peekable(),
+        expr: expr,
+    };
+    let mut tokens = Vec::new();
+    loop {
+        let tuple = try!(lexer.next_token());
+        let is_eof = tuple.token == Token::Eof;
+        tokens.push(tuple);
+        if is_eof {
+            break;
+        }
+    }
+    Ok(tokens)
+}
+
+struct Lexer<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    expr: &'a str,
+}
+
+impl<'a> Lexer<'a> {
+    /// The byte offset of the next unconsumed character (or the end of
+    /// the expression), used as the exclusive end of the token just
+    /// produced.
+    fn pos(&mut self) -> usize {
+        self.chars.peek().map(|&(o, _)| o).unwrap_or_else(|| self.expr.len())
+    }
+
+    fn next_token(&mut self) -> Result<TokenTuple, String> {
+        loop {
+            match self.chars.peek().cloned() {
+                None => {
+                    let eof = self.expr.len();
+                    return Ok(TokenTuple { token: Token::Eof, offset: eof, end: eof });
+                }
+                Some((_, c)) if c.is_whitespace() => {
+                    self.chars.next();
+                    continue;
+                }
+                Some((offset, c)) => return self.single(offset, c),
+            }
+        }
+    }
+
+    fn single(&mut self, offset: usize, c: char) -> Result<TokenTuple, String> {
+        self.chars.next();
+        let token = match c {
+            '.' => Token::Dot,
+            '*' => Token::Star,
+            '@' => Token::At,
+            '(' => Token::Lparen,
+            ')' => Token::Rparen,
+            '{' => Token::Lbrace,
+            '}' => Token::Rbrace,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            '?' => Token::Question,
+            '&' => self.one_or_two('&', Token::Ampersand, Token::And),
+            '|' => self.one_or_two('|', Token::Pipe, Token::Or),
+            '!' => self.one_or_two('=', Token::Not, Token::Ne),
+            '=' => {
+                if let Some(&(_, '=')) = self.chars.peek() {
+                    self.chars.next();
+                    Token::Eq
+                } else {
+                    return Err("Expected `==`, found a single `=`".to_string());
+                }
+            }
+            '<' => self.one_or_two('=', Token::Lt, Token::Lte),
+            '>' => self.one_or_two('=', Token::Gt, Token::Gte),
+            '[' => {
+                if let Some(&(_, ']')) = self.chars.peek() {
+                    self.chars.next();
+                    Token::Flatten
+                } else {
+                    Token::Lbracket
+                }
+            }
+            ']' => Token::Rbracket,
+            '\'' | '"' => return self.quoted_string(offset, c),
+            '`' => return self.raw_literal(offset),
+            '-' | '0'..='9' => return self.number(offset, c),
+            c if c.is_alphabetic() || c == '_' => return self.identifier(offset, c),
+            other => return Err(format!("Unexpected character {:?}", other)),
+        };
+        let end = self.pos();
+        Ok(TokenTuple { token: token, offset: offset, end: end })
+    }
+
+    fn one_or_two(&mut self, expect: char, single: Token, double: Token) -> Token {
+        if let Some(&(_, c)) = self.chars.peek() {
+            if c == expect {
+                self.chars.next();
+                return double;
+            }
+        }
+        single
+    }
+
+    fn identifier(&mut self, offset: usize, first: char) -> Result<TokenTuple, String> {
+        let mut name = String::new();
+        name.push(first);
+        while let Some(&(_, c)) = self.chars.peek() {
+            if c.is_alphanumeric() || c == '_' {
+                name.push(c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+        let end = self.pos();
+        Ok(TokenTuple { token: Token::Identifier(name), offset: offset, end: end })
+    }
+
+    fn number(&mut self, offset: usize, first: char) -> Result<TokenTuple, String> {
+        let mut raw = String::new();
+        raw.push(first);
+        while let Some(&(_, c)) = self.chars.peek() {
+            if c.is_digit(10) {
+                raw.push(c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+        let end = self.pos();
+        match raw.parse::<i32>() {
+            Ok(n) => Ok(TokenTuple { token: Token::Number(n), offset: offset, end: end }),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Lexes a `"quoted identifier"` or a `'raw string literal'`.
+    ///
+    /// The two share the same escaping rules but mean different things to
+    /// the parser: a double-quoted string names a field (just like an
+    /// unquoted identifier, only able to hold characters an unquoted
+    /// identifier can't), while a single-quoted string is a string literal
+    /// value and must produce a `Literal` token, not a field name.
+    fn quoted_string(&mut self, offset: usize, quote: char) -> Result<TokenTuple, String> {
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, c)) if c == quote => break,
+                Some((_, '\\')) => {
+                    if let Some((_, escaped)) = self.chars.next() {
+                        value.push(escaped);
+                    }
+                }
+                Some((_, c)) => value.push(c),
+                None => return Err("Unclosed quoted string".to_string()),
+            }
+        }
+        let end = self.pos();
+        if quote == '"' {
+            Ok(TokenTuple { token: Token::Identifier(value), offset: offset, end: end })
+        } else {
+            let var = ::variable::Variable::String(value);
+            Ok(TokenTuple { token: Token::Literal(::std::rc::Rc::new(var)), offset: offset, end: end })
+        }
+    }
+
+    fn raw_literal(&mut self, offset: usize) -> Result<TokenTuple, String> {
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '`')) => break,
+                Some((_, '\\')) => {
+                    if let Some((_, escaped)) = self.chars.next() {
+                        value.push(escaped);
+                    }
+                }
+                Some((_, c)) => value.push(c),
+                None => return Err("Unclosed raw string literal".to_string()),
+            }
+        }
+        let end = self.pos();
+        let var = try!(::variable::Variable::from_json(&value).map_err(|e| e.to_string()));
+        Ok(TokenTuple { token: Token::Literal(::std::rc::Rc::new(var)), offset: offset, end: end })
+    }
+}
@@ -42,11 +42,11 @@
 //!
 //! ```
 //! use jmespath;
-//! use jmespath::ast::Ast;
+//! use jmespath::ast::{Ast, Span};
 //!
 //! let expr = jmespath::Expression::new("foo").unwrap();
 //! assert_eq!("foo", expr.as_str());
-//! assert_eq!(&Ast::Field {name: "foo".to_string(), offset: 0}, expr.as_ast());
+//! assert_eq!(&Ast::Field {name: "foo".to_string(), span: Span::new(0, 3)}, expr.as_ast());
 //! ```
 //!
 //! # Using `jmespath::search`
@@ -90,17 +90,21 @@ use std::fmt;
 use std::rc::Rc;
 
 use self::serde::Serialize;
+use self::serde::ser::{SerializeStruct, Serializer as SerdeSerializer};
 
 use ast::Ast;
 use variable::Serializer;
 use interpreter::{TreeInterpreter, Context, SearchResult};
+use vm::{Program, Vm};
 
 pub mod ast;
 pub mod functions;
 mod parser;
 mod lexer;
 pub mod interpreter;
+pub mod runtime;
 mod variable;
+pub mod vm;
 
 pub type RcVar = Rc<Variable>;
 
@@ -139,6 +143,42 @@ impl Error {
             error_reason: error_reason
         }
     }
+
+    /// Renders a stable, machine-readable diagnostic describing the error.
+    ///
+    /// The returned object always carries `severity`, a short `code`
+    /// (e.g. `"invalid-type"`), a human-readable `message`, and a `span`
+    /// with the `offset`/`line`/`column` of the failure. Runtime variants
+    /// that carry additional detail (`expected`, `actual`, `position`,
+    /// `actual_value`) attach those fields to the `span` as well, so
+    /// editors, LSPs, and CI tooling can consume failures without
+    /// scraping the caret-rendered `Display` string.
+    pub fn to_diagnostic_json(&self) -> serde_json::Value {
+        let mut span = serde_json::Map::new();
+        span.insert("offset".to_string(), serde_json::Value::from(self.coordinates.offset));
+        span.insert("line".to_string(), serde_json::Value::from(self.coordinates.line));
+        span.insert("column".to_string(), serde_json::Value::from(self.coordinates.column));
+        span.insert("length".to_string(), serde_json::Value::from(self.coordinates.length));
+
+        if let ErrorReason::Runtime(ref runtime_error) = self.error_reason {
+            runtime_error.populate_span(&mut span);
+        }
+
+        let mut object = serde_json::Map::new();
+        object.insert("severity".to_string(), serde_json::Value::from("error"));
+        object.insert("code".to_string(), serde_json::Value::from(self.error_reason.code()));
+        object.insert("message".to_string(), serde_json::Value::from(self.error_reason.to_string()));
+        object.insert("span".to_string(), serde_json::Value::Object(span));
+        serde_json::Value::Object(object)
+    }
+}
+
+impl Serialize for Error {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: SerdeSerializer
+    {
+        self.to_diagnostic_json().serialize(serializer)
+    }
 }
 
 impl fmt::Display for Error {
@@ -166,6 +206,29 @@ impl fmt::Display for ErrorReason {
     }
 }
 
+impl ErrorReason {
+    /// A short, stable machine-readable code identifying the error kind,
+    /// suitable for editors/LSPs and CI tooling to match on (e.g.
+    /// `"invalid-type"`, `"unknown-function"`, `"parse"`).
+    fn code(&self) -> &'static str {
+        match self {
+            &ErrorReason::Parse(_) => "parse",
+            &ErrorReason::Runtime(ref e) => e.code(),
+        }
+    }
+}
+
+impl Serialize for ErrorReason {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: SerdeSerializer
+    {
+        let mut state = try!(serializer.serialize_struct("ErrorReason", 2));
+        try!(state.serialize_field("code", self.code()));
+        try!(state.serialize_field("message", &self.to_string()));
+        state.end()
+    }
+}
+
 /// Runtime JMESPath error
 #[derive(Clone,Debug,PartialEq)]
 pub enum RuntimeError {
@@ -234,7 +297,76 @@ impl fmt::Display for RuntimeError {
     }
 }
 
-/// Defines the coordinates to a position in an expression string.
+impl RuntimeError {
+    /// A short, stable machine-readable code identifying the runtime
+    /// error kind.
+    fn code(&self) -> &'static str {
+        use self::RuntimeError::*;
+        match self {
+            &InvalidSlice => "invalid-slice",
+            &InvalidKey(_) => "invalid-key",
+            &TooManyArguments { .. } => "too-many-arguments",
+            &NotEnoughArguments { .. } => "not-enough-arguments",
+            &UnknownFunction(_) => "unknown-function",
+            &InvalidType { .. } => "invalid-type",
+            &InvalidReturnType { .. } => "invalid-return-type",
+        }
+    }
+
+    /// Adds any additional diagnostic fields this variant knows about
+    /// (`expected`, `actual`, `position`, `actual_value`) to the span
+    /// object used by `Error::to_diagnostic_json`.
+    fn populate_span(&self, span: &mut serde_json::Map<String, serde_json::Value>) {
+        use self::RuntimeError::*;
+        match self {
+            &TooManyArguments { ref expected, ref actual } |
+            &NotEnoughArguments { ref expected, ref actual } => {
+                span.insert("expected".to_string(), serde_json::Value::from(*expected));
+                span.insert("actual".to_string(), serde_json::Value::from(*actual));
+            },
+            &InvalidType { ref expected, ref actual, ref actual_value, ref position } => {
+                span.insert("expected".to_string(), serde_json::Value::from(expected.clone()));
+                span.insert("actual".to_string(), serde_json::Value::from(actual.clone()));
+                span.insert("position".to_string(), serde_json::Value::from(*position));
+                span.insert("actual_value".to_string(),
+                    serde_json::to_value(&**actual_value).unwrap_or(serde_json::Value::Null));
+            },
+            &InvalidReturnType { ref expected, ref actual, ref actual_value, ref position,
+                    ref invocation } => {
+                span.insert("expected".to_string(), serde_json::Value::from(expected.clone()));
+                span.insert("actual".to_string(), serde_json::Value::from(actual.clone()));
+                span.insert("position".to_string(), serde_json::Value::from(*position));
+                span.insert("invocation".to_string(), serde_json::Value::from(*invocation));
+                span.insert("actual_value".to_string(),
+                    serde_json::to_value(&**actual_value).unwrap_or(serde_json::Value::Null));
+            },
+            &InvalidKey(ref actual) => {
+                span.insert("actual".to_string(), serde_json::Value::from(actual.clone()));
+            },
+            &UnknownFunction(ref name) => {
+                span.insert("actual".to_string(), serde_json::Value::from(name.clone()));
+            },
+            &InvalidSlice => {},
+        }
+    }
+}
+
+impl Serialize for RuntimeError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: SerdeSerializer
+    {
+        let mut span = serde_json::Map::new();
+        self.populate_span(&mut span);
+        let mut state = try!(serializer.serialize_struct("RuntimeError", 3));
+        try!(state.serialize_field("code", self.code()));
+        try!(state.serialize_field("message", &self.to_string()));
+        try!(state.serialize_field("details", &serde_json::Value::Object(span)));
+        state.end()
+    }
+}
+
+/// Defines the coordinates to a position (or range) in an expression
+/// string.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Coordinates {
     /// Absolute character position.
@@ -243,16 +375,34 @@ pub struct Coordinates {
     pub line: usize,
     /// Column of the line number.
     pub column: usize,
+    /// Number of characters to underline starting at `column`, so that
+    /// the *whole* offending token can be highlighted rather than just
+    /// its first character.
+    pub length: usize,
 }
 
 impl Coordinates {
     /// Create an expression coordinates struct based on an offset
     // position in the expression.
     pub fn from_offset(expr: &str, offset: usize) -> Coordinates {
-        // Find each new line and create a formatted error message.
+        Coordinates::from_span(expr, offset, offset)
+    }
+
+    /// Create an expression coordinates struct that underlines the range
+    /// `[start, end)` of the expression, e.g. to highlight an entire
+    /// `foo(...)` call or `[::0]` slice rather than just its first byte.
+    pub fn from_span(expr: &str, start: usize, end: usize) -> Coordinates {
+        // `start`/`end` are byte offsets (as produced by the lexer's
+        // char_indices-based scanning), so walk char_indices and stop once
+        // the byte index reaches `start` rather than counting `chars()`,
+        // or a multi-byte character before the span would throw off the
+        // column by the number of extra bytes it takes to encode.
         let mut current_line: usize = 0;
         let mut current_col: usize = 0;
-        for c in expr.chars().take(offset) {
+        for (byte_offset, c) in expr.char_indices() {
+            if byte_offset >= start {
+                break;
+            }
             match c {
                 '\n' => {
                     current_line += 1;
@@ -261,16 +411,23 @@ impl Coordinates {
                 _ => current_col += 1
             }
         }
+        let length = if end > start {
+            expr.get(start..end).map(|s| s.chars().count()).unwrap_or(end - start)
+        } else {
+            1
+        };
         Coordinates {
             line: current_line,
             column: current_col,
-            offset: offset
+            offset: start,
+            length: length,
         }
     }
 
     fn inject_carat(&self, buff: &mut String) {
         buff.push_str(&(0..self.column).map(|_| ' ').collect::<String>());
-        buff.push_str(&"^\n");
+        buff.push_str(&(0..self.length).map(|_| '^').collect::<String>());
+        buff.push('\n');
     }
 
     /// Returns a string that shows the expression and a carat pointing to
@@ -303,9 +460,23 @@ impl fmt::Display for Coordinates {
     }
 }
 
+impl Serialize for Coordinates {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: SerdeSerializer
+    {
+        let mut state = try!(serializer.serialize_struct("Coordinates", 4));
+        try!(state.serialize_field("offset", &self.offset));
+        try!(state.serialize_field("line", &self.line));
+        try!(state.serialize_field("column", &self.column));
+        try!(state.serialize_field("length", &self.length));
+        state.end()
+    }
+}
+
 /// A compiled JMESPath expression.
 pub struct Expression<'a> {
     ast: Ast,
+    program: Program,
     original: String,
     interpreter: Option<&'a TreeInterpreter>
 }
@@ -323,14 +494,25 @@ impl<'a> Expression<'a> {
     pub fn with_interpreter(expression: &str,
                             interpreter: Option<&'a TreeInterpreter>)
                             -> Result<Expression<'a>, Error> {
+        let ast = try!(parse(expression));
+        let program = vm::compile(&ast);
         Ok(Expression {
             original: expression.to_string(),
-            ast: try!(parse(expression)),
+            ast: ast,
+            program: program,
             interpreter: interpreter
         })
     }
 
     /// Returns the result of searching data with the compiled expression.
+    ///
+    /// The expression is compiled once (in `new`/`with_interpreter`) into a
+    /// flat `vm::Program` and evaluated by `Vm::run`, which walks it with an
+    /// explicit operand stack rather than recursing over the `Ast`. This
+    /// keeps deep pipelines (`a | b | c | ...`) from growing the Rust call
+    /// stack. `interpreter::TreeInterpreter::interpret` still walks the
+    /// `Ast` directly and remains available as a tree-walking fallback for
+    /// callers that want to evaluate `as_ast()` themselves.
     pub fn search<T: Serialize>(&self, data: T) -> SearchResult {
         let mut ser = Serializer::new();
         data.serialize(&mut ser).ok().unwrap();
@@ -338,12 +520,12 @@ impl<'a> Expression<'a> {
         match self.interpreter {
             Some(i) => {
                 let mut ctx = Context::new(i, &self.original);
-                i.interpret(&data, &self.ast, &mut ctx)
+                Vm::new(i).run(&self.program, &data, &mut ctx)
             },
             None => {
                 let interpreter = TreeInterpreter::new();
                 let mut ctx = Context::new(&interpreter, &self.original);
-                interpreter.interpret(&data, &self.ast, &mut ctx)
+                Vm::new(&interpreter).run(&self.program, &data, &mut ctx)
             }
         }
     }
@@ -386,6 +568,9 @@ mod test {
 
     use super::*;
     use super::ast::Ast;
+    use super::functions::{Function, Signature, ArgumentType};
+    use super::interpreter::Context;
+    use super::runtime::Runtime;
 
     #[test]
     fn formats_expression_as_string_or_debug() {
@@ -415,7 +600,7 @@ mod test {
     #[test]
     fn can_get_expression_ast() {
         let expr = Expression::new("foo").unwrap();
-        assert_eq!(&Ast::Field {offset: 0, name: "foo".to_string()}, expr.as_ast());
+        assert_eq!(&Ast::Field {span: ast::Span::new(0, 3), name: "foo".to_string()}, expr.as_ast());
     }
 
     #[test]
@@ -447,4 +632,132 @@ mod test {
         assert_eq!(4, coords.offset);
         assert_eq!("foo..bar\n    ^\n", coords.expression_with_carat(expr));
     }
+
+    #[test]
+    fn coordinates_underline_a_range_of_characters() {
+        let expr = "foo(bar)";
+        let coords = Coordinates::from_span(expr, 0, 8);
+        assert_eq!(0, coords.column);
+        assert_eq!(8, coords.length);
+        assert_eq!("foo(bar)\n^^^^^^^^\n", coords.expression_with_carat(expr));
+    }
+
+    #[test]
+    fn coordinates_account_for_multi_byte_characters_before_the_span() {
+        // "é" is 2 bytes in UTF-8, so the byte offset of `n` (7) is one
+        // past its character position (6).
+        let expr = "héllo.nope(@)";
+        let coords = Coordinates::from_offset(expr, 7);
+        assert_eq!(0, coords.line);
+        assert_eq!(6, coords.column);
+    }
+
+    #[test]
+    fn unknown_function_errors_underline_the_whole_call() {
+        let expr = Expression::new("nope(@)");
+        assert!(expr.is_ok());
+        let err = search("nope(@)", Variable::Null).unwrap_err();
+        assert_eq!(7, err.coordinates.length);
+        assert_eq!("nope(@)\n^^^^^^^\n", err.coordinates.expression_with_carat("nope(@)"));
+    }
+
+    #[test]
+    fn renders_diagnostic_json_for_parse_errors() {
+        let err = Error::new("foo..bar", 4, ErrorReason::Parse("Unexpected token".to_string()));
+        let json = err.to_diagnostic_json();
+        assert_eq!("error", json["severity"]);
+        assert_eq!("parse", json["code"]);
+        assert_eq!(4, json["span"]["offset"]);
+        assert_eq!(0, json["span"]["line"]);
+        assert_eq!(4, json["span"]["column"]);
+    }
+
+    #[test]
+    fn renders_diagnostic_json_for_unknown_function_errors() {
+        let reason = ErrorReason::Runtime(RuntimeError::UnknownFunction("foo".to_string()));
+        let err = Error::new("foo(@)", 0, reason);
+        let json = err.to_diagnostic_json();
+        assert_eq!("unknown-function", json["code"]);
+        assert_eq!("foo", json["span"]["actual"]);
+    }
+
+    #[test]
+    fn evaluates_deep_pipe_chains_via_the_compiled_vm() {
+        let expr = "a.b | c.d | e.f | g.h | i.j | k.l | m.n | o.p | q.r | s.t";
+        let data = Variable::from_json(
+            "{\"a\":{\"b\":{\"c\":{\"d\":{\"e\":{\"f\":{\"g\":{\"h\":{\"i\":{\"j\":\
+             {\"k\":{\"l\":{\"m\":{\"n\":{\"o\":{\"p\":{\"q\":{\"r\":{\"s\":\
+             {\"t\":42}}}}}}}}}}}}}}}}}}}}").unwrap();
+        assert_eq!(Rc::new(Variable::Number(42f64)), search(expr, data).unwrap());
+    }
+
+    #[test]
+    fn evaluates_projections_and_functions_via_the_compiled_vm() {
+        let data = Variable::from_json("[{\"a\":1},{\"a\":2},{\"a\":3}]").unwrap();
+        assert_eq!(Rc::new(Variable::Number(3f64)), search("length(@)", data.clone()).unwrap());
+
+        let expected = Variable::from_json("[1, 2, 3]").unwrap();
+        assert_eq!(Rc::new(expected), search("@[*].a", data).unwrap());
+    }
+
+    #[test]
+    fn evaluates_and_or_and_comparisons_via_the_compiled_vm() {
+        let data = Variable::from_json("{\"a\":1,\"b\":2}").unwrap();
+        assert_eq!(Rc::new(Variable::Bool(true)), search("a < b", data.clone()).unwrap());
+        assert_eq!(Rc::new(Variable::Number(2f64)), search("a && b", data.clone()).unwrap());
+        assert_eq!(Rc::new(Variable::Number(1f64)), search("a || b", data).unwrap());
+    }
+
+    #[test]
+    fn renders_diagnostic_json_for_invalid_type_errors() {
+        let reason = ErrorReason::Runtime(RuntimeError::InvalidType {
+            expected: "string".to_string(),
+            actual: "number".to_string(),
+            actual_value: Rc::new(Variable::Bool(true)),
+            position: 0,
+        });
+        let err = Error::new("foo(@)", 4, reason);
+        let json = err.to_diagnostic_json();
+        assert_eq!("invalid-type", json["code"]);
+        assert_eq!("string", json["span"]["expected"]);
+        assert_eq!("number", json["span"]["actual"]);
+        assert_eq!(0, json["span"]["position"]);
+    }
+
+    struct DoubleFn;
+
+    impl Function for DoubleFn {
+        fn signature(&self) -> Signature {
+            Signature::exact(1, vec![vec![ArgumentType::Number]])
+        }
+
+        fn evaluate(&self, args: &[RcVar], _ctx: &Context) -> Result<RcVar, Error> {
+            let value = match *args[0] {
+                Variable::Number(n) => n,
+                _ => unreachable!("Signature::validate guarantees a number"),
+            };
+            Ok(Rc::new(Variable::Number(value * 2f64)))
+        }
+    }
+
+    #[test]
+    fn runtime_searches_with_a_custom_registered_function() {
+        let mut runtime = Runtime::new();
+        runtime.register_function("double", DoubleFn);
+        let expr = runtime.compile("double(@)").unwrap();
+        assert_eq!(Rc::new(Variable::Number(8f64)), expr.search(4).unwrap());
+    }
+
+    #[test]
+    fn runtime_still_validates_arity_and_types_for_custom_functions() {
+        let mut runtime = Runtime::new();
+        runtime.register_function("double", DoubleFn);
+        let expr = runtime.compile("double(@)").unwrap();
+        let err = expr.search("not a number").unwrap_err();
+        assert_eq!("invalid-type", err.to_diagnostic_json()["code"]);
+
+        let expr = runtime.compile("double(@, @)").unwrap();
+        let err = expr.search(4).unwrap_err();
+        assert_eq!("too-many-arguments", err.to_diagnostic_json()["code"]);
+    }
 }
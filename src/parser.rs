@@ -0,0 +1,386 @@
+//! Parses a JMESPath expression string into an `Ast`.
+
+use ast::{Ast, Comparator, KeyValuePair, Span};
+use lexer::{tokenize, Token, TokenTuple};
+use {Error, ErrorReason};
+
+/// The result of parsing an expression.
+pub type ParseResult = Result<Ast, Error>;
+
+/// Parses a JMESPath expression into an `Ast`.
+pub fn parse(expr: &str) -> ParseResult {
+    let tokens = try!(tokenize(expr).map_err(|e| Error::new(expr, 0, ErrorReason::Parse(e))));
+    let mut parser = Parser { tokens: tokens, pos: 0, expr: expr, depth: 0 };
+    let ast = try!(parser.expression(0));
+    try!(parser.expect_eof());
+    Ok(ast)
+}
+
+/// The deepest `expression` can recurse before parsing gives up with a
+/// parse error instead of overflowing the stack. `expression` recurses
+/// once per chained operator (`.`, `|`, etc.) and once per nesting level
+/// (parens, `[...]`, `{...}`), so a pathological expression like 50,000
+/// dot-joined fields would otherwise blow the stack before the VM ever
+/// sees it.
+const MAX_EXPRESSION_DEPTH: usize = 512;
+
+struct Parser<'a> {
+    tokens: Vec<TokenTuple>,
+    pos: usize,
+    expr: &'a str,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].token
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens[self.pos].offset
+    }
+
+    /// The exclusive end offset of the most recently consumed token, used
+    /// as the end of a span that was just finished parsing.
+    fn last_end(&self) -> usize {
+        self.tokens[self.pos.saturating_sub(1)].end
+    }
+
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(start, self.last_end())
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].token.clone();
+        if self.pos + 1 < self.tokens.len() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn err(&self, message: &str) -> Error {
+        Error::new(self.expr, self.offset(), ErrorReason::Parse(message.to_string()))
+    }
+
+    fn expect_eof(&self) -> Result<(), Error> {
+        match *self.peek() {
+            Token::Eof => Ok(()),
+            _ => Err(self.err("Unexpected trailing tokens")),
+        }
+    }
+
+    /// Parses an expression using precedence climbing, stopping once a
+    /// token of lower binding power than `min_bp` is encountered.
+    fn expression(&mut self, min_bp: u8) -> ParseResult {
+        self.depth += 1;
+        if self.depth > MAX_EXPRESSION_DEPTH {
+            self.depth -= 1;
+            return Err(self.err("Expression nested too deeply"));
+        }
+        let result = self.expression_uncounted(min_bp);
+        self.depth -= 1;
+        result
+    }
+
+    fn expression_uncounted(&mut self, min_bp: u8) -> ParseResult {
+        let mut lhs = try!(self.nud());
+        loop {
+            let bp = binding_power(self.peek());
+            if bp == 0 || bp < min_bp {
+                break;
+            }
+            lhs = try!(self.led(lhs, bp));
+        }
+        Ok(lhs)
+    }
+
+    /// Parses a prefix (null denotation) expression.
+    fn nud(&mut self) -> ParseResult {
+        let offset = self.offset();
+        match self.advance() {
+            Token::At => Ok(Ast::Identity { span: self.span_from(offset) }),
+            Token::Identifier(name) => Ok(Ast::Field { name: name, span: self.span_from(offset) }),
+            Token::Literal(value) => Ok(Ast::Literal { value: value, span: self.span_from(offset) }),
+            Token::Flatten => {
+                let rhs = try!(self.projection_rhs(offset));
+                let span = self.span_from(offset);
+                Ok(Ast::Projection {
+                    lhs: Box::new(Ast::Flatten {
+                        node: Box::new(Ast::Identity { span: Span::point(offset) }),
+                        span: span,
+                    }),
+                    rhs: Box::new(rhs),
+                    span: span,
+                })
+            }
+            Token::Star => {
+                let rhs = try!(self.projection_rhs(offset));
+                let span = self.span_from(offset);
+                Ok(Ast::Projection {
+                    lhs: Box::new(Ast::ObjectValues {
+                        node: Box::new(Ast::Identity { span: Span::point(offset) }),
+                        span: span,
+                    }),
+                    rhs: Box::new(rhs),
+                    span: span,
+                })
+            }
+            Token::Not => {
+                let node = try!(self.expression(PRECEDENCE_NOT));
+                Ok(Ast::Not { node: Box::new(node), span: self.span_from(offset) })
+            }
+            Token::Lparen => {
+                let node = try!(self.expression(0));
+                try!(self.eat(Token::Rparen, "Expected )"));
+                Ok(node)
+            }
+            Token::Lbracket => self.multi_list(offset),
+            Token::Lbrace => self.multi_hash(offset),
+            Token::Ampersand => {
+                let node = try!(self.expression(PRECEDENCE_EXPREF));
+                Ok(Ast::Expref { ast: Box::new(node), span: self.span_from(offset) })
+            }
+            other => Err(Error::new(self.expr, offset,
+                ErrorReason::Parse(format!("Unexpected token {:?}", other)))),
+        }
+    }
+
+    /// Parses an infix (left denotation) expression given a parsed `lhs`.
+    fn led(&mut self, lhs: Ast, bp: u8) -> ParseResult {
+        let offset = lhs.span().start;
+        match self.advance() {
+            Token::Dot => {
+                let rhs = try!(self.expression(bp));
+                Ok(Ast::Subexpr { lhs: Box::new(lhs), rhs: Box::new(rhs), span: self.span_from(offset) })
+            }
+            Token::Pipe => {
+                let rhs = try!(self.expression(bp));
+                Ok(Ast::Subexpr { lhs: Box::new(lhs), rhs: Box::new(rhs), span: self.span_from(offset) })
+            }
+            Token::Or => {
+                let rhs = try!(self.expression(bp));
+                Ok(Ast::Or { lhs: Box::new(lhs), rhs: Box::new(rhs), span: self.span_from(offset) })
+            }
+            Token::And => {
+                let rhs = try!(self.expression(bp));
+                Ok(Ast::And { lhs: Box::new(lhs), rhs: Box::new(rhs), span: self.span_from(offset) })
+            }
+            Token::Lbracket => self.index_or_slice(lhs, offset),
+            Token::Flatten => {
+                let rhs = try!(self.projection_rhs(offset));
+                Ok(Ast::Projection {
+                    lhs: Box::new(Ast::Flatten { node: Box::new(lhs), span: self.span_from(offset) }),
+                    rhs: Box::new(rhs),
+                    span: self.span_from(offset),
+                })
+            }
+            Token::Eq => self.comparison(lhs, Comparator::Eq, bp, offset),
+            Token::Ne => self.comparison(lhs, Comparator::Ne, bp, offset),
+            Token::Lt => self.comparison(lhs, Comparator::Lt, bp, offset),
+            Token::Lte => self.comparison(lhs, Comparator::Lte, bp, offset),
+            Token::Gt => self.comparison(lhs, Comparator::Gt, bp, offset),
+            Token::Gte => self.comparison(lhs, Comparator::Gte, bp, offset),
+            Token::Question => {
+                let then = try!(self.expression(bp));
+                Ok(Ast::Condition {
+                    predicate: Box::new(lhs),
+                    then: Box::new(then),
+                    span: self.span_from(offset),
+                })
+            }
+            Token::Lparen => {
+                let name = match lhs {
+                    Ast::Field { name, .. } => name,
+                    _ => return Err(self.err("Expected a function name")),
+                };
+                let args = try!(self.call_args());
+                Ok(Ast::Function { name: name, args: args, span: self.span_from(offset) })
+            }
+            other => Err(Error::new(self.expr, offset,
+                ErrorReason::Parse(format!("Unexpected infix token {:?}", other)))),
+        }
+    }
+
+    fn comparison(&mut self, lhs: Ast, comparator: Comparator, bp: u8, offset: usize) -> ParseResult {
+        let rhs = try!(self.expression(bp));
+        Ok(Ast::Comparison {
+            comparator: comparator,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+            span: self.span_from(offset),
+        })
+    }
+
+    fn projection_rhs(&mut self, offset: usize) -> ParseResult {
+        match *self.peek() {
+            Token::Dot => {
+                self.advance();
+                self.expression(PRECEDENCE_PROJECTION)
+            }
+            Token::Lbracket => self.expression(PRECEDENCE_PROJECTION),
+            _ => Ok(Ast::Identity { span: Span::point(offset) }),
+        }
+    }
+
+    fn index_or_slice(&mut self, lhs: Ast, offset: usize) -> ParseResult {
+        if *self.peek() == Token::Star {
+            self.advance();
+            try!(self.eat(Token::Rbracket, "Expected ] after [*"));
+            let rhs = try!(self.projection_rhs(offset));
+            return Ok(Ast::Projection {
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+                span: self.span_from(offset),
+            });
+        }
+
+        let mut parts: Vec<Option<i32>> = vec![None, None, None];
+        let mut part = 0;
+        let mut saw_colon = false;
+        loop {
+            match self.peek().clone() {
+                Token::Number(n) => {
+                    self.advance();
+                    parts[part] = Some(n);
+                }
+                Token::Colon => {
+                    self.advance();
+                    saw_colon = true;
+                    part += 1;
+                    if part > 2 {
+                        return Err(self.err("Too many colons in slice expression"));
+                    }
+                }
+                Token::Rbracket => {
+                    self.advance();
+                    break;
+                }
+                _ => return Err(self.err("Expected number, `:`, or `]`")),
+            }
+        }
+
+        let index_span = self.span_from(offset);
+        let index_ast = if saw_colon {
+            Ast::Slice {
+                start: parts[0],
+                stop: parts[1],
+                step: parts[2].unwrap_or(1),
+                span: index_span,
+            }
+        } else {
+            Ast::Index { idx: parts[0].unwrap_or(0), span: index_span }
+        };
+
+        if saw_colon {
+            let subexpr_span = self.span_from(offset);
+            let rhs = try!(self.projection_rhs(offset));
+            let projection_span = self.span_from(offset);
+            Ok(Ast::Projection {
+                lhs: Box::new(Ast::Subexpr {
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(index_ast),
+                    span: subexpr_span,
+                }),
+                rhs: Box::new(rhs),
+                span: projection_span,
+            })
+        } else {
+            Ok(Ast::Subexpr { lhs: Box::new(lhs), rhs: Box::new(index_ast), span: index_span })
+        }
+    }
+
+    fn multi_list(&mut self, offset: usize) -> ParseResult {
+        let mut elements = Vec::new();
+        if *self.peek() != Token::Rbracket {
+            loop {
+                elements.push(try!(self.expression(0)));
+                match *self.peek() {
+                    Token::Comma => { self.advance(); }
+                    Token::Rbracket => break,
+                    _ => return Err(self.err("Expected `,` or `]`")),
+                }
+            }
+        }
+        try!(self.eat(Token::Rbracket, "Expected ]"));
+        Ok(Ast::MultiList { elements: elements, span: self.span_from(offset) })
+    }
+
+    fn multi_hash(&mut self, offset: usize) -> ParseResult {
+        let mut elements = Vec::new();
+        if *self.peek() != Token::Rbrace {
+            loop {
+                let key = match self.advance() {
+                    Token::Identifier(name) => name,
+                    other => return Err(Error::new(self.expr, self.offset(),
+                        ErrorReason::Parse(format!("Expected key name, found {:?}", other)))),
+                };
+                try!(self.eat(Token::Colon, "Expected `:` after key name"));
+                let value = try!(self.expression(0));
+                elements.push(KeyValuePair { key: key, value: value });
+                match *self.peek() {
+                    Token::Comma => { self.advance(); }
+                    Token::Rbrace => break,
+                    _ => return Err(self.err("Expected `,` or `}`")),
+                }
+            }
+        }
+        try!(self.eat(Token::Rbrace, "Expected }"));
+        Ok(Ast::MultiHash { elements: elements, span: self.span_from(offset) })
+    }
+
+    fn call_args(&mut self) -> Result<Vec<Ast>, Error> {
+        let mut args = Vec::new();
+        if *self.peek() != Token::Rparen {
+            loop {
+                args.push(try!(self.expression(0)));
+                match *self.peek() {
+                    Token::Comma => { self.advance(); }
+                    Token::Rparen => break,
+                    _ => return Err(self.err("Expected `,` or `)`")),
+                }
+            }
+        }
+        try!(self.eat(Token::Rparen, "Expected )"));
+        Ok(args)
+    }
+
+    fn eat(&mut self, expected: Token, message: &str) -> Result<(), Error> {
+        if *self.peek() == expected {
+            self.advance();
+            Ok(())
+        } else {
+            Err(self.err(message))
+        }
+    }
+}
+
+const PRECEDENCE_OR: u8 = 1;
+const PRECEDENCE_AND: u8 = 2;
+const PRECEDENCE_NOT: u8 = 3;
+const PRECEDENCE_COMPARISON: u8 = 4;
+const PRECEDENCE_PIPE: u8 = 5;
+// `.` must bind *tighter* than the projection floor below it, or a
+// projection's rhs would only ever absorb a single `.field` hop before the
+// precedence climb in `expression` stops (e.g. `foo[*].bar.baz` would parse
+// as `foo[*].bar` followed by a dangling `.baz`). Matches jmespath.py, where
+// `dot` (40) outranks `star`/`flatten` (20).
+const PRECEDENCE_PROJECTION: u8 = 6;
+const PRECEDENCE_DOT: u8 = 7;
+const PRECEDENCE_EXPREF: u8 = 8;
+const PRECEDENCE_INDEX: u8 = 9;
+
+fn binding_power(token: &Token) -> u8 {
+    match *token {
+        Token::Pipe => PRECEDENCE_PIPE,
+        Token::Or => PRECEDENCE_OR,
+        Token::And => PRECEDENCE_AND,
+        Token::Eq | Token::Ne | Token::Lt | Token::Lte | Token::Gt | Token::Gte =>
+            PRECEDENCE_COMPARISON,
+        Token::Dot => PRECEDENCE_DOT,
+        Token::Lbracket => PRECEDENCE_INDEX,
+        Token::Flatten => PRECEDENCE_INDEX,
+        Token::Lparen => PRECEDENCE_INDEX,
+        Token::Question => PRECEDENCE_OR,
+        _ => 0,
+    }
+}
@@ -0,0 +1,40 @@
+//! An ergonomic entry point for registering custom JMESPath functions.
+//!
+//! `Expression::with_interpreter` accepts a `&TreeInterpreter`, but building
+//! one by hand means constructing a `HashMap` of boxed `Function` trait
+//! objects yourself. `Runtime` owns that map and exposes a single
+//! `register_function`/`compile` pair, the way Rocket exposes ad-hoc
+//! request validators or jrsonnet registers native functions -- a first
+//! class extension point that doesn't require forking the `functions`
+//! module.
+
+use functions::Function;
+use interpreter::TreeInterpreter;
+use {Error, Expression};
+
+/// Owns a `TreeInterpreter` and lets callers register custom functions
+/// before compiling expressions against it.
+pub struct Runtime {
+    interpreter: TreeInterpreter,
+}
+
+impl Runtime {
+    /// Creates a new runtime with the default set of built-in functions
+    /// registered.
+    pub fn new() -> Runtime {
+        Runtime { interpreter: TreeInterpreter::new() }
+    }
+
+    /// Registers `function` under `name`, making it callable from any
+    /// expression compiled with `Runtime::compile`. Overwrites any
+    /// existing function (built-in or otherwise) registered under the
+    /// same name.
+    pub fn register_function<F: Function + 'static>(&mut self, name: &str, function: F) {
+        self.interpreter.register_function(name, Box::new(function));
+    }
+
+    /// Compiles `expression` against this runtime's functions.
+    pub fn compile<'a>(&'a self, expression: &str) -> Result<Expression<'a>, Error> {
+        Expression::with_interpreter(expression, Some(&self.interpreter))
+    }
+}
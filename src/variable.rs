@@ -0,0 +1,451 @@
+//! The `Variable` type used as both the input and output of a search.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer as SerdeSerializer};
+use serde_json;
+
+use ast::Ast;
+use RcVar;
+
+/// A dynamically typed value used as JMESPath input and output data.
+#[derive(Clone, Debug)]
+pub enum Variable {
+    Null,
+    String(String),
+    Bool(bool),
+    Number(f64),
+    Array(Vec<RcVar>),
+    Object(BTreeMap<String, RcVar>),
+    /// An unevaluated expression reference created with `&expr`.
+    Expref(Ast),
+}
+
+impl Variable {
+    /// Parses a JSON string into a `Variable`.
+    pub fn from_json(json_str: &str) -> Result<Variable, String> {
+        serde_json::from_str::<serde_json::Value>(json_str)
+            .map(|v| Variable::from(&v))
+            .map_err(|e| e.to_string())
+    }
+
+    /// Returns true if the variable is `Null`.
+    pub fn is_null(&self) -> bool {
+        matches!(*self, Variable::Null)
+    }
+
+    /// Returns the bool value if the variable is a `Bool`.
+    pub fn as_boolean(&self) -> Option<bool> {
+        match *self {
+            Variable::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// Returns the string value if the variable is a `String`.
+    pub fn as_string(&self) -> Option<&String> {
+        match *self {
+            Variable::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the array value if the variable is an `Array`.
+    pub fn as_array(&self) -> Option<&Vec<RcVar>> {
+        match *self {
+            Variable::Array(ref a) => Some(a),
+            _ => None,
+        }
+    }
+
+    /// Returns the object value if the variable is an `Object`.
+    pub fn as_object(&self) -> Option<&BTreeMap<String, RcVar>> {
+        match *self {
+            Variable::Object(ref o) => Some(o),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the variable is considered truthy by JMESPath.
+    pub fn is_truthy(&self) -> bool {
+        match *self {
+            Variable::Bool(b) => b,
+            Variable::Null => false,
+            Variable::String(ref s) => !s.is_empty(),
+            Variable::Array(ref a) => !a.is_empty(),
+            Variable::Object(ref o) => !o.is_empty(),
+            Variable::Number(_) => true,
+            Variable::Expref(_) => true,
+        }
+    }
+
+    /// Returns the JMESPath type name of the variable (e.g. `"string"`).
+    pub fn get_type(&self) -> &str {
+        match *self {
+            Variable::String(_) => "string",
+            Variable::Number(_) => "number",
+            Variable::Bool(_) => "boolean",
+            Variable::Array(_) => "array",
+            Variable::Object(_) => "object",
+            Variable::Null => "null",
+            Variable::Expref(_) => "expref",
+        }
+    }
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(fmt, "{}", serde_json::to_string(self).unwrap_or_default())
+    }
+}
+
+impl PartialEq for Variable {
+    fn eq(&self, other: &Variable) -> bool {
+        match (self, other) {
+            (&Variable::Null, &Variable::Null) => true,
+            (&Variable::Bool(a), &Variable::Bool(b)) => a == b,
+            (&Variable::Number(a), &Variable::Number(b)) => a == b,
+            (&Variable::String(ref a), &Variable::String(ref b)) => a == b,
+            (&Variable::Array(ref a), &Variable::Array(ref b)) => a == b,
+            (&Variable::Object(ref a), &Variable::Object(ref b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl<'a> From<&'a serde_json::Value> for Variable {
+    fn from(value: &serde_json::Value) -> Variable {
+        match *value {
+            serde_json::Value::Null => Variable::Null,
+            serde_json::Value::Bool(b) => Variable::Bool(b),
+            serde_json::Value::Number(ref n) => Variable::Number(n.as_f64().unwrap_or(0.0)),
+            serde_json::Value::String(ref s) => Variable::String(s.clone()),
+            serde_json::Value::Array(ref a) => {
+                Variable::Array(a.iter().map(|v| ::std::rc::Rc::new(Variable::from(v))).collect())
+            }
+            serde_json::Value::Object(ref o) => {
+                let mut map = BTreeMap::new();
+                for (k, v) in o.iter() {
+                    map.insert(k.clone(), ::std::rc::Rc::new(Variable::from(v)));
+                }
+                Variable::Object(map)
+            }
+        }
+    }
+}
+
+impl Serialize for Variable {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: SerdeSerializer
+    {
+        match *self {
+            Variable::Null => serializer.serialize_unit(),
+            Variable::Bool(b) => serializer.serialize_bool(b),
+            Variable::Number(n) => serializer.serialize_f64(n),
+            Variable::String(ref s) => serializer.serialize_str(s),
+            Variable::Array(ref a) => {
+                // `Vec<Rc<Variable>>`'s blanket impl only exists behind
+                // serde's non-default "rc" feature, which this crate
+                // doesn't enable; serialize elements by hand through
+                // `&**v` instead of depending on that feature.
+                let mut seq = try!(serializer.serialize_seq(Some(a.len())));
+                for v in a {
+                    try!(seq.serialize_element(&**v));
+                }
+                seq.end()
+            }
+            Variable::Object(ref o) => {
+                let mut map = try!(serializer.serialize_map(Some(o.len())));
+                for (k, v) in o {
+                    try!(map.serialize_entry(k, &**v));
+                }
+                map.end()
+            }
+            Variable::Expref(_) => serializer.serialize_str("<expref>"),
+        }
+    }
+}
+
+/// A `serde::Serializer` that builds a `Variable` from any `Serialize`
+/// type, allowing arbitrary Rust values to be searched without an
+/// explicit coercion to `Variable`.
+pub struct Serializer {
+    value: Variable,
+}
+
+impl Serializer {
+    pub fn new() -> Serializer {
+        Serializer { value: Variable::Null }
+    }
+
+    /// Consumes the serializer and returns the built `Variable`.
+    pub fn unwrap(self) -> Variable {
+        self.value
+    }
+}
+
+fn serialize_to_variable<T: ?Sized + Serialize>(value: &T) -> Variable {
+    let mut ser = Serializer::new();
+    // Serializing into our own `Serializer` never produces an `Err`.
+    value.serialize(&mut ser).ok();
+    ser.unwrap()
+}
+
+impl<'a> SerdeSerializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = serde_json::Error;
+    type SerializeSeq = SeqSerializer<'a>;
+    type SerializeTuple = SeqSerializer<'a>;
+    type SerializeTupleStruct = SeqSerializer<'a>;
+    type SerializeTupleVariant = SeqSerializer<'a>;
+    type SerializeMap = MapSerializer<'a>;
+    type SerializeStruct = MapSerializer<'a>;
+    type SerializeStructVariant = MapSerializer<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Self::Error> {
+        self.value = Variable::Bool(v);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), Self::Error> { self.serialize_f64(v as f64) }
+    fn serialize_i16(self, v: i16) -> Result<(), Self::Error> { self.serialize_f64(v as f64) }
+    fn serialize_i32(self, v: i32) -> Result<(), Self::Error> { self.serialize_f64(v as f64) }
+    fn serialize_i64(self, v: i64) -> Result<(), Self::Error> { self.serialize_f64(v as f64) }
+    fn serialize_u8(self, v: u8) -> Result<(), Self::Error> { self.serialize_f64(v as f64) }
+    fn serialize_u16(self, v: u16) -> Result<(), Self::Error> { self.serialize_f64(v as f64) }
+    fn serialize_u32(self, v: u32) -> Result<(), Self::Error> { self.serialize_f64(v as f64) }
+    fn serialize_u64(self, v: u64) -> Result<(), Self::Error> { self.serialize_f64(v as f64) }
+    fn serialize_f32(self, v: f32) -> Result<(), Self::Error> { self.serialize_f64(v as f64) }
+
+    fn serialize_f64(self, v: f64) -> Result<(), Self::Error> {
+        self.value = Variable::Number(v);
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), Self::Error> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), Self::Error> {
+        self.value = Variable::String(v.to_string());
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Self::Error> {
+        let elements = v.iter().map(|b| Rc::new(Variable::Number(*b as f64))).collect();
+        self.value = Variable::Array(elements);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), Self::Error> { self.serialize_unit() }
+
+    fn serialize_some<T>(self, value: &T) -> Result<(), Self::Error>
+        where T: ?Sized + Serialize
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Self::Error> {
+        self.value = Variable::Null;
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Self::Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str)
+        -> Result<(), Self::Error>
+    {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<(), Self::Error>
+        where T: ?Sized + Serialize
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(self, _name: &'static str, _index: u32,
+            variant: &'static str, value: &T) -> Result<(), Self::Error>
+        where T: ?Sized + Serialize
+    {
+        let mut map = BTreeMap::new();
+        map.insert(variant.to_string(), Rc::new(serialize_to_variable(value)));
+        self.value = Variable::Object(map);
+        Ok(())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(SeqSerializer { parent: self, elements: Vec::new() })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize)
+        -> Result<Self::SerializeTupleStruct, Self::Error>
+    {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, _variant: &'static str,
+            len: usize) -> Result<Self::SerializeTupleVariant, Self::Error>
+    {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(MapSerializer { parent: self, entries: BTreeMap::new(), pending_key: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize)
+        -> Result<Self::SerializeStruct, Self::Error>
+    {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, _variant: &'static str,
+            len: usize) -> Result<Self::SerializeStructVariant, Self::Error>
+    {
+        self.serialize_map(Some(len))
+    }
+}
+
+/// Accumulates sequence elements while serializing into a `Variable::Array`.
+pub struct SeqSerializer<'a> {
+    parent: &'a mut Serializer,
+    elements: Vec<RcVar>,
+}
+
+impl<'a> ::serde::ser::SerializeSeq for SeqSerializer<'a> {
+    type Ok = ();
+    type Error = serde_json::Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+        where T: ?Sized + Serialize
+    {
+        self.elements.push(Rc::new(serialize_to_variable(value)));
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        self.parent.value = Variable::Array(self.elements);
+        Ok(())
+    }
+}
+
+impl<'a> ::serde::ser::SerializeTuple for SeqSerializer<'a> {
+    type Ok = ();
+    type Error = serde_json::Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+        where T: ?Sized + Serialize
+    {
+        ::serde::ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        ::serde::ser::SerializeSeq::end(self)
+    }
+}
+
+impl<'a> ::serde::ser::SerializeTupleStruct for SeqSerializer<'a> {
+    type Ok = ();
+    type Error = serde_json::Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+        where T: ?Sized + Serialize
+    {
+        ::serde::ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        ::serde::ser::SerializeSeq::end(self)
+    }
+}
+
+impl<'a> ::serde::ser::SerializeTupleVariant for SeqSerializer<'a> {
+    type Ok = ();
+    type Error = serde_json::Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+        where T: ?Sized + Serialize
+    {
+        ::serde::ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        ::serde::ser::SerializeSeq::end(self)
+    }
+}
+
+/// Accumulates key/value entries while serializing into a
+/// `Variable::Object`.
+pub struct MapSerializer<'a> {
+    parent: &'a mut Serializer,
+    entries: BTreeMap<String, RcVar>,
+    pending_key: Option<String>,
+}
+
+impl<'a> ::serde::ser::SerializeMap for MapSerializer<'a> {
+    type Ok = ();
+    type Error = serde_json::Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+        where T: ?Sized + Serialize
+    {
+        self.pending_key = match serialize_to_variable(key) {
+            Variable::String(s) => Some(s),
+            other => Some(other.to_string()),
+        };
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+        where T: ?Sized + Serialize
+    {
+        let key = self.pending_key.take().unwrap_or_default();
+        self.entries.insert(key, Rc::new(serialize_to_variable(value)));
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        self.parent.value = Variable::Object(self.entries);
+        Ok(())
+    }
+}
+
+impl<'a> ::serde::ser::SerializeStruct for MapSerializer<'a> {
+    type Ok = ();
+    type Error = serde_json::Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+        where T: ?Sized + Serialize
+    {
+        self.entries.insert(key.to_string(), Rc::new(serialize_to_variable(value)));
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        ::serde::ser::SerializeMap::end(self)
+    }
+}
+
+impl<'a> ::serde::ser::SerializeStructVariant for MapSerializer<'a> {
+    type Ok = ();
+    type Error = serde_json::Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+        where T: ?Sized + Serialize
+    {
+        ::serde::ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<(), Self::Error> {
+        ::serde::ser::SerializeMap::end(self)
+    }
+}
@@ -0,0 +1,393 @@
+//! Compiles an `Ast` into a flat `Program` of `Instruction`s and evaluates
+//! it with an explicit operand stack instead of recursing over the tree.
+//!
+//! `TreeInterpreter::interpret` recurses once per `Ast` node, so a long
+//! `a | b | c | ...` pipeline or a deeply nested subexpression chain
+//! recurses proportionally to its depth and can overflow the stack. This
+//! module flattens `Subexpr`/`Pipe` chains (by far the most common source
+//! of depth) into a single `Vec<Instruction>` that a `Vm` walks with a
+//! program counter, so evaluating `a.b.c. ... .z` costs one loop instead
+//! of `z` stack frames. Function-call arguments are compiled inline into
+//! the same program. `Projection` bodies are still evaluated through a
+//! recursive call into the VM (one per nested projection), since their
+//! nesting in real-world expressions tracks the *structure* of the query
+//! rather than an arbitrarily long chain.
+//!
+//! Every `Instruction` carries the `Span` of the `Ast` node it was
+//! compiled from, so `Context::span` (and therefore `Error::from_ctx`)
+//! still reports accurate coordinates when the VM raises a runtime error.
+
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use ast::{Ast, Comparator, Span};
+use interpreter::{self, Context, SearchResult, TreeInterpreter};
+use variable::Variable;
+use {Error, ErrorReason, RcVar, RuntimeError};
+
+/// A flattened, directly-executable form of an `Ast`.
+#[derive(Clone, Debug)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+}
+
+#[derive(Clone, Debug)]
+struct Instruction {
+    op: Op,
+    span: Span,
+}
+
+#[derive(Clone, Debug)]
+enum Op {
+    /// Replaces the current value with a literal, ignoring it.
+    PushLiteral(RcVar),
+    /// Replaces the current value with an expression reference.
+    PushExpref(Ast),
+    /// Looks up a field by name on the current value.
+    LoadField(String),
+    /// Indexes into the current value.
+    Index(i32),
+    /// Slices the current value.
+    Slice { start: Option<i32>, stop: Option<i32>, step: i32 },
+    /// Flattens the current value one level.
+    Flatten,
+    /// Replaces an object with the vec of its values.
+    ObjectValues,
+    /// Replaces the current value with its boolean negation.
+    Not,
+    /// Saves a copy of the current value on the context stack.
+    SaveCtx,
+    /// Pops the context stack into the current value.
+    RestoreCtx,
+    /// Pops and discards the top of the context stack.
+    DropCtx,
+    /// Copies (without popping) the top of the context stack into the
+    /// current value.
+    LoadCtx,
+    /// Pushes a copy of the current value onto the operand stack.
+    PushOperand,
+    /// Unconditional jump.
+    Jump(usize),
+    /// Jumps if the current value is falsy.
+    JumpIfFalsy(usize),
+    /// Jumps if the current value is truthy.
+    JumpIfTruthy(usize),
+    /// Pops one operand and compares it against the current value.
+    Compare(Comparator),
+    /// Pops `argc` operands (oldest first) and invokes a function.
+    CallFunction { name: String, argc: usize },
+    /// Pops `len` operands (oldest first) into an array.
+    MakeArray(usize),
+    /// Pops `keys.len()` operands (oldest first) into an object keyed by
+    /// `keys`, in order.
+    MakeObject(Vec<String>),
+    /// Projects the compiled sub-program over each element of an array,
+    /// collecting the non-null results.
+    Project(Program),
+}
+
+struct Compiler {
+    instructions: Vec<Instruction>,
+}
+
+impl Compiler {
+    fn emit(&mut self, op: Op, span: Span) -> usize {
+        self.instructions.push(Instruction { op: op, span: span });
+        self.instructions.len() - 1
+    }
+
+    fn patch_jump(&mut self, at: usize, target: usize) {
+        let span = self.instructions[at].span;
+        self.instructions[at] = Instruction {
+            op: match self.instructions[at].op {
+                Op::Jump(_) => Op::Jump(target),
+                Op::JumpIfFalsy(_) => Op::JumpIfFalsy(target),
+                Op::JumpIfTruthy(_) => Op::JumpIfTruthy(target),
+                _ => unreachable!("patch_jump called on a non-jump instruction"),
+            },
+            span: span,
+        };
+    }
+
+    fn compile(&mut self, ast: &Ast) {
+        let span = ast.span();
+        match *ast {
+            Ast::Identity { .. } => {}
+            Ast::Literal { ref value, .. } => {
+                self.emit(Op::PushLiteral(value.clone()), span);
+            }
+            Ast::Expref { ref ast, .. } => {
+                self.emit(Op::PushExpref((**ast).clone()), span);
+            }
+            Ast::Field { ref name, .. } => {
+                self.emit(Op::LoadField(name.clone()), span);
+            }
+            Ast::Index { idx, .. } => {
+                self.emit(Op::Index(idx), span);
+            }
+            Ast::Slice { start, stop, step, .. } => {
+                self.emit(Op::Slice { start: start, stop: stop, step: step }, span);
+            }
+            Ast::Flatten { ref node, .. } => {
+                self.compile(node);
+                self.emit(Op::Flatten, span);
+            }
+            Ast::ObjectValues { ref node, .. } => {
+                self.compile(node);
+                self.emit(Op::ObjectValues, span);
+            }
+            Ast::Not { ref node, .. } => {
+                self.compile(node);
+                self.emit(Op::Not, span);
+            }
+            Ast::Subexpr { ref lhs, ref rhs, .. } => {
+                // The core flattening: `lhs.rhs` is simply `lhs`'s
+                // instructions followed by `rhs`'s -- no recursive call
+                // is needed to move from one to the next.
+                self.compile(lhs);
+                self.compile(rhs);
+            }
+            Ast::And { ref lhs, ref rhs, .. } => {
+                self.emit(Op::SaveCtx, span);
+                self.compile(lhs);
+                let to_end = self.emit(Op::JumpIfFalsy(0), span);
+                self.emit(Op::RestoreCtx, span);
+                self.compile(rhs);
+                let to_after = self.emit(Op::Jump(0), span);
+                let end = self.emit(Op::DropCtx, span);
+                self.patch_jump(to_end, end);
+                let after = self.instructions.len();
+                self.patch_jump(to_after, after);
+            }
+            Ast::Or { ref lhs, ref rhs, .. } => {
+                self.emit(Op::SaveCtx, span);
+                self.compile(lhs);
+                let to_end = self.emit(Op::JumpIfTruthy(0), span);
+                self.emit(Op::RestoreCtx, span);
+                self.compile(rhs);
+                let to_after = self.emit(Op::Jump(0), span);
+                let end = self.emit(Op::DropCtx, span);
+                self.patch_jump(to_end, end);
+                let after = self.instructions.len();
+                self.patch_jump(to_after, after);
+            }
+            Ast::Condition { ref predicate, ref then, .. } => {
+                self.emit(Op::SaveCtx, span);
+                self.compile(predicate);
+                let to_else = self.emit(Op::JumpIfFalsy(0), span);
+                self.emit(Op::RestoreCtx, span);
+                self.compile(then);
+                let to_after = self.emit(Op::Jump(0), span);
+                let else_start = self.emit(Op::PushLiteral(Rc::new(Variable::Null)), span);
+                self.emit(Op::DropCtx, span);
+                self.patch_jump(to_else, else_start);
+                let after = self.instructions.len();
+                self.patch_jump(to_after, after);
+            }
+            Ast::Comparison { comparator, ref lhs, ref rhs, .. } => {
+                self.emit(Op::SaveCtx, span);
+                self.compile(lhs);
+                self.emit(Op::PushOperand, span);
+                self.emit(Op::RestoreCtx, span);
+                self.compile(rhs);
+                self.emit(Op::Compare(comparator), span);
+            }
+            Ast::MultiList { ref elements, .. } => {
+                self.emit(Op::SaveCtx, span);
+                for element in elements {
+                    self.emit(Op::LoadCtx, span);
+                    self.compile(element);
+                    self.emit(Op::PushOperand, span);
+                }
+                self.emit(Op::DropCtx, span);
+                self.emit(Op::MakeArray(elements.len()), span);
+            }
+            Ast::MultiHash { ref elements, .. } => {
+                self.emit(Op::SaveCtx, span);
+                let mut keys = Vec::with_capacity(elements.len());
+                for kvp in elements {
+                    self.emit(Op::LoadCtx, span);
+                    self.compile(&kvp.value);
+                    self.emit(Op::PushOperand, span);
+                    keys.push(kvp.key.clone());
+                }
+                self.emit(Op::DropCtx, span);
+                self.emit(Op::MakeObject(keys), span);
+            }
+            Ast::Function { ref name, ref args, .. } => {
+                self.emit(Op::SaveCtx, span);
+                for arg in args {
+                    self.emit(Op::LoadCtx, span);
+                    self.compile(arg);
+                    self.emit(Op::PushOperand, span);
+                }
+                self.emit(Op::DropCtx, span);
+                self.emit(Op::CallFunction { name: name.clone(), argc: args.len() }, span);
+            }
+            Ast::Projection { ref lhs, ref rhs, .. } => {
+                self.compile(lhs);
+                self.emit(Op::Project(compile(rhs)), span);
+            }
+        }
+    }
+}
+
+/// Compiles an `Ast` into a flat, directly-executable `Program`.
+pub fn compile(ast: &Ast) -> Program {
+    let mut compiler = Compiler { instructions: Vec::new() };
+    compiler.compile(ast);
+    Program { instructions: compiler.instructions }
+}
+
+/// Executes a compiled `Program` against a `Variable`, matching the
+/// null-propagation semantics of `TreeInterpreter::interpret` exactly.
+pub struct Vm<'a> {
+    interpreter: &'a TreeInterpreter,
+}
+
+impl<'a> Vm<'a> {
+    /// Creates a new VM bound to the given interpreter (used to resolve
+    /// function calls).
+    pub fn new(interpreter: &'a TreeInterpreter) -> Vm<'a> {
+        Vm { interpreter: interpreter }
+    }
+
+    /// Runs `program` against `data`, returning the resulting `RcVar`.
+    pub fn run(&self, program: &Program, data: &RcVar, ctx: &mut Context) -> SearchResult {
+        let mut current = data.clone();
+        let mut ctx_stack: Vec<RcVar> = Vec::new();
+        let mut operand_stack: Vec<RcVar> = Vec::new();
+        let mut pc = 0;
+
+        while pc < program.instructions.len() {
+            let instr = &program.instructions[pc];
+            ctx.span = instr.span;
+            match instr.op {
+                Op::PushLiteral(ref value) => current = value.clone(),
+                Op::PushExpref(ref ast) => current = Rc::new(Variable::Expref(ast.clone())),
+                Op::LoadField(ref name) => {
+                    current = match *current {
+                        Variable::Object(ref map) => {
+                            map.get(name).cloned().unwrap_or_else(|| Rc::new(Variable::Null))
+                        }
+                        _ => Rc::new(Variable::Null),
+                    };
+                }
+                Op::Index(idx) => {
+                    current = match *current {
+                        Variable::Array(ref array) => interpreter::index(array, idx),
+                        _ => Rc::new(Variable::Null),
+                    };
+                }
+                Op::Slice { start, stop, step } => {
+                    if step == 0 {
+                        return Err(Error::from_ctx(ctx,
+                            ErrorReason::Runtime(RuntimeError::InvalidSlice)));
+                    }
+                    current = match *current {
+                        Variable::Array(ref array) => {
+                            Rc::new(Variable::Array(interpreter::slice(array, start, stop, step)))
+                        }
+                        _ => Rc::new(Variable::Null),
+                    };
+                }
+                Op::Flatten => {
+                    current = match *current {
+                        Variable::Array(ref outer) => {
+                            let mut flattened = Vec::new();
+                            for item in outer {
+                                match **item {
+                                    Variable::Array(ref inner) => flattened.extend(inner.clone()),
+                                    _ => flattened.push(item.clone()),
+                                }
+                            }
+                            Rc::new(Variable::Array(flattened))
+                        }
+                        _ => Rc::new(Variable::Null),
+                    };
+                }
+                Op::ObjectValues => {
+                    current = match *current {
+                        Variable::Object(ref map) => {
+                            Rc::new(Variable::Array(map.values().cloned().collect()))
+                        }
+                        _ => Rc::new(Variable::Null),
+                    };
+                }
+                Op::Not => current = Rc::new(Variable::Bool(!current.is_truthy())),
+                Op::SaveCtx => ctx_stack.push(current.clone()),
+                Op::RestoreCtx => current = ctx_stack.pop().expect("unbalanced SaveCtx/RestoreCtx"),
+                Op::DropCtx => { ctx_stack.pop().expect("unbalanced SaveCtx/DropCtx"); }
+                Op::LoadCtx => {
+                    current = ctx_stack.last().expect("LoadCtx with empty context stack").clone();
+                }
+                Op::PushOperand => operand_stack.push(current.clone()),
+                Op::Jump(target) => {
+                    pc = target;
+                    continue;
+                }
+                Op::JumpIfFalsy(target) => {
+                    if !current.is_truthy() {
+                        pc = target;
+                        continue;
+                    }
+                }
+                Op::JumpIfTruthy(target) => {
+                    if current.is_truthy() {
+                        pc = target;
+                        continue;
+                    }
+                }
+                Op::Compare(comparator) => {
+                    let lhs = operand_stack.pop().expect("Compare with empty operand stack");
+                    current = Rc::new(Variable::Bool(interpreter::compare(comparator, &lhs, &current)));
+                }
+                Op::CallFunction { ref name, argc } => {
+                    let args = pop_n(&mut operand_stack, argc);
+                    match self.interpreter.function(name) {
+                        Some(function) => {
+                            try!(function.signature().validate(&args, ctx));
+                            current = try!(function.evaluate(&args, ctx));
+                        }
+                        None => return Err(Error::from_ctx(ctx,
+                            ErrorReason::Runtime(RuntimeError::UnknownFunction(name.clone())))),
+                    }
+                }
+                Op::MakeArray(len) => {
+                    current = Rc::new(Variable::Array(pop_n(&mut operand_stack, len)));
+                }
+                Op::MakeObject(ref keys) => {
+                    let values = pop_n(&mut operand_stack, keys.len());
+                    let mut map = BTreeMap::new();
+                    for (key, value) in keys.iter().zip(values) {
+                        map.insert(key.clone(), value);
+                    }
+                    current = Rc::new(Variable::Object(map));
+                }
+                Op::Project(ref sub_program) => {
+                    current = match *current {
+                        Variable::Array(ref array) => {
+                            let mut collected = Vec::new();
+                            for element in array {
+                                let result = try!(self.run(sub_program, element, ctx));
+                                if !result.is_null() {
+                                    collected.push(result);
+                                }
+                            }
+                            Rc::new(Variable::Array(collected))
+                        }
+                        _ => Rc::new(Variable::Null),
+                    };
+                }
+            }
+            pc += 1;
+        }
+
+        Ok(current)
+    }
+}
+
+fn pop_n(stack: &mut Vec<RcVar>, n: usize) -> Vec<RcVar> {
+    let split_at = stack.len() - n;
+    stack.split_off(split_at)
+}
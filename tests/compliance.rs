@@ -0,0 +1,82 @@
+//! Data-driven conformance tests.
+//!
+//! Loads every `.json` file under `tests/compliance/`, each holding an
+//! array of groups shaped like the community JMESPath compliance format (a
+//! `given` document plus a list of `{expression, result}` or `{expression,
+//! error}` cases), and drives each case through `jmespath::search`.
+//!
+//! These fixtures are hand-authored for this crate, not a vendored copy of
+//! the upstream `jmespath.test` corpus — they're intentionally shaped like
+//! it so the real corpus can be dropped in here later, but until that
+//! happens don't read "compliance suite" as "passes the official test
+//! suite". They cover projections, multi-select, slices, pipes, raw
+//! string literals, comparisons and boolean operators, and function
+//! arity errors, so a regression in any of those shows up here instead of
+//! only in hand-picked unit tests. Argument type errors, `?` conditions,
+//! expression references and `*` object projections have no cases here.
+//!
+//! TODO: replace (or supplement) these with the upstream `jmespath.test`
+//! compliance corpus (https://github.com/jmespath/jmespath.test) — this
+//! environment had no network access to vendor it when these fixtures
+//! were written. Drop its `tests/` JSON files into `tests/compliance/`;
+//! the loader below just reads every `.json` file in the directory.
+
+extern crate jmespath;
+extern crate serde_json;
+
+use std::fs;
+use std::path::Path;
+
+use jmespath::Variable;
+
+#[test]
+fn runs_the_compliance_suite() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/compliance");
+    let mut cases_run = 0;
+
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let contents = fs::read_to_string(&path).unwrap();
+        let groups: serde_json::Value = serde_json::from_str(&contents)
+            .unwrap_or_else(|e| panic!("{}: invalid JSON: {}", path.display(), e));
+
+        for group in groups.as_array().expect("each file is an array of groups") {
+            let given = Variable::from(&group["given"]);
+            let cases = group["cases"].as_array().expect("each group has a `cases` array");
+
+            for case in cases {
+                let expression = case["expression"].as_str().expect("case has an `expression`");
+                cases_run += 1;
+
+                if let Some(expected) = case.get("result") {
+                    let expected = Variable::from(expected);
+                    let actual = jmespath::search(expression, given.clone()).unwrap_or_else(|e| {
+                        panic!("{} ({:?}): expected {:?}, got error: {}",
+                            path.display(), expression, expected, e)
+                    });
+                    assert_eq!(expected, *actual, "{} ({:?})", path.display(), expression);
+                } else if let Some(expected_code) = case.get("error").and_then(|e| e.as_str()) {
+                    let result = jmespath::search(expression, given.clone());
+                    let err = match result {
+                        Err(err) => err,
+                        Ok(value) => panic!("{} ({:?}): expected a {:?} error, got a result: {:?}",
+                            path.display(), expression, expected_code, value),
+                    };
+                    let code = err.to_diagnostic_json()["code"].as_str().unwrap().to_string();
+                    assert_eq!(expected_code, code, "{} ({:?})", path.display(), expression);
+                } else {
+                    panic!("{} ({:?}): case has neither `result` nor `error`",
+                        path.display(), expression);
+                }
+            }
+        }
+    }
+
+    assert!(cases_run > 0, "expected the compliance suite to contain at least one case");
+}